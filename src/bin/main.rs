@@ -1,12 +1,35 @@
 
 
-use clap::Parser;
-use rust_cssr::CSSR;
+use clap::{Parser, ValueEnum};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use rust_cssr::{MergeCriterion, CSSR};
+use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs;
+use std::io;
 use std::path::Path;
 
+/// How to tokenize the input file into a `u32` symbol stream.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum InputFormat {
+    /// One `u32` symbol per line (the original format).
+    Ints,
+    /// A continuous character stream, e.g. a DNA string like `ACGTACGT...`.
+    Chars,
+    /// Whitespace-separated tokens.
+    Tokens,
+}
+
+/// How to export the reconstructed ε-machine, instead of printing it.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum EmitFormat {
+    /// GraphViz DOT, suitable for piping into `dot`.
+    Dot,
+    /// The per-state transition matrix and its alphabet.
+    Matrix,
+}
+
 /// Command-line arguments for the CSSR algorithm.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -15,6 +38,10 @@ struct Args {
     #[arg(short, long)]
     file: String,
 
+    /// How the input file is tokenized into symbols.
+    #[arg(long, value_enum, default_value_t = InputFormat::Ints)]
+    input_format: InputFormat,
+
     /// Maximum history length to consider.
     #[arg(short, long, default_value_t = 10)]
     max_history: usize,
@@ -22,23 +49,89 @@ struct Args {
     /// Significance level for the chi-square test.
     #[arg(short, long, default_value_t = 0.05)]
     alpha: f32,
+
+    /// Instead of printing the reconstructed states, sample this many
+    /// symbols from the reconstructed ε-machine.
+    #[arg(long)]
+    generate: Option<usize>,
+
+    /// Seed for the generator's RNG, for reproducible `--generate` runs.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Print statistical complexity and entropy rate (raw and Aitken
+    /// Δ²-accelerated) for the reconstructed ε-machine.
+    #[arg(long)]
+    metrics: bool,
+
+    /// Export the reconstructed ε-machine in this format instead of printing
+    /// the causal states.
+    #[arg(long, value_enum)]
+    emit: Option<EmitFormat>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    match read_data(&args.file) {
-        Ok(data) => {
+    match read_data(&args.file, args.input_format) {
+        Ok((data, symbols)) => {
             let alphabet: HashSet<u32> = data.iter().cloned().collect();
 
             let mut cssr = CSSR::new(alphabet);
-            cssr.run(&data, args.max_history, args.alpha);
+            let criterion = MergeCriterion::ChiSquare {
+                alpha: args.alpha as f64,
+            };
+            cssr.run(&data, args.max_history, criterion);
+
+            if let Some(n) = args.generate {
+                let machine = cssr.build_machine(&data);
+                let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+                let sequence = machine.sample(&mut rng, n);
+                let rendered: Vec<&str> = sequence.iter().map(|s| symbol_label(*s, &symbols)).collect();
+                println!("{:?}", rendered);
+                return;
+            }
+
+            if args.metrics {
+                let machine = cssr.build_machine(&data);
+                let (raw, accelerated) =
+                    cssr.entropy_rate_extrapolated(&data, args.max_history, criterion);
+                println!("Statistical complexity: {}", machine.statistical_complexity());
+                println!("Entropy rate (raw): {}", raw);
+                println!("Entropy rate (Aitken-accelerated): {}", accelerated);
+                return;
+            }
+
+            if let Some(emit) = args.emit {
+                let machine = cssr.build_machine(&data);
+                match emit {
+                    EmitFormat::Dot => print!("{}", machine.to_dot(Some(&symbols))),
+                    EmitFormat::Matrix => {
+                        let (matrix, alphabet) = machine.to_transition_matrix();
+                        println!("Alphabet: {:?}", alphabet);
+                        for (i, row) in matrix.iter().enumerate() {
+                            println!("State {}: {:?}", i, row);
+                        }
+                    }
+                }
+                return;
+            }
 
             println!("Number of causal states: {}", cssr.states.len());
             for (i, state) in cssr.states.iter().enumerate() {
                 println!("State {}:", i);
-                println!("  Histories: {:?}", state.histories);
-                println!("  Next symbol distribution: {:?}", state.next_symbol_dist);
+                let histories: Vec<Vec<&str>> = state
+                    .histories
+                    .iter()
+                    .map(|h| h.iter().map(|s| symbol_label(*s, &symbols)).collect())
+                    .collect();
+                println!("  Histories: {:?}", histories);
+                let dist: Vec<(&str, f32)> = state
+                    .next_symbol_dist
+                    .iter()
+                    .map(|(s, p)| (symbol_label(*s, &symbols), *p))
+                    .collect();
+                println!("  Next symbol distribution: {:?}", dist);
             }
         }
         Err(e) => {
@@ -47,18 +140,65 @@ fn main() {
     }
 }
 
-fn read_data<P>(filename: P) -> Result<Vec<u32>, io::Error>
+/// Looks up a symbol's original textual form in `symbols` (which every input
+/// format fills, `Ints` included), falling back to `<unknown symbol>` when
+/// the symbol has no entry.
+fn symbol_label(symbol: u32, symbols: &HashMap<u32, String>) -> &str {
+    symbols
+        .get(&symbol)
+        .map(String::as_str)
+        .unwrap_or("<unknown symbol>")
+}
+
+/// Reads the input file and tokenizes it into a `u32` symbol stream according
+/// to `format`, returning the stream alongside a reverse map from symbol id
+/// back to its original textual form (so printed output can show the source
+/// symbols rather than internal ids).
+fn read_data<P>(filename: P, format: InputFormat) -> io::Result<(Vec<u32>, HashMap<u32, String>)>
 where
     P: AsRef<Path>,
 {
-    let file = File::open(filename)?;
-    io::BufReader::new(file)
-        .lines()
-        .map(|line| {
-            line.and_then(|l| {
-                l.parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            })
-        })
-        .collect()
+    let content = fs::read_to_string(filename)?;
+
+    match format {
+        InputFormat::Ints => {
+            let data: Vec<u32> = content
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    line.parse()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })
+                .collect::<io::Result<_>>()?;
+            let symbols = data.iter().map(|&s| (s, s.to_string())).collect();
+            Ok((data, symbols))
+        }
+        InputFormat::Chars => Ok(tokenize(
+            content.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_string()),
+        )),
+        InputFormat::Tokens => Ok(tokenize(content.split_whitespace().map(str::to_string))),
+    }
+}
+
+/// Assigns a stable `u32` id to each distinct token on first appearance,
+/// returning the resulting symbol stream and the id-to-token reverse map.
+fn tokenize(tokens: impl Iterator<Item = String>) -> (Vec<u32>, HashMap<u32, String>) {
+    let mut id_of: HashMap<String, u32> = HashMap::new();
+    let mut symbols: HashMap<u32, String> = HashMap::new();
+    let mut data = Vec::new();
+
+    for token in tokens {
+        let id = match id_of.get(&token) {
+            Some(&id) => id,
+            None => {
+                let id = id_of.len() as u32;
+                id_of.insert(token.clone(), id);
+                symbols.insert(id, token);
+                id
+            }
+        };
+        data.push(id);
+    }
+
+    (data, symbols)
 }
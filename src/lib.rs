@@ -2,7 +2,9 @@
 
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use rayon::prelude::*;
 use statrs::distribution::{ChiSquared, ContinuousCDF};
+use statrs::function::gamma::ln_gamma;
 
 /// Represents a history of symbols.
 pub type History = Vec<u32>;
@@ -18,6 +20,11 @@ pub struct CausalState {
     pub histories: HashSet<History>,
     /// The probability distribution of the next symbol.
     pub next_symbol_dist: HashMap<u32, f32>,
+    /// Raw next-symbol occurrence counts backing `next_symbol_dist`, kept
+    /// alongside it so merge criteria that need integer counts (e.g. the
+    /// Dirichlet-multinomial Bayes factor) don't have to reverse-engineer
+    /// them from normalized floats.
+    pub counts: HashMap<u32, u32>,
 }
 
 impl CausalState {
@@ -26,6 +33,7 @@ impl CausalState {
         CausalState {
             histories: HashSet::new(),
             next_symbol_dist: HashMap::new(),
+            counts: HashMap::new(),
         }
     }
 }
@@ -56,6 +64,39 @@ pub struct CSSR {
     pub states: HashSet<CausalState>,
     /// The alphabet of the input data.
     pub alphabet: HashSet<u32>,
+    /// A one-pass index of next-symbol counts per observed history, built by
+    /// `ensure_history_counts` on first use. Replaces rescanning `data` for
+    /// every candidate state at every history length; reused across `run`
+    /// calls (e.g. with different `alpha`) as long as `max_history` hasn't
+    /// grown. The cache is not keyed on `data`: a later call with different
+    /// data reuses the counts built from the earlier data.
+    history_counts: HashMap<History, HashMap<u32, u32>>,
+    /// The `max_history` that `history_counts` was built for. `run` can be
+    /// called again with a smaller or equal `max_history` and reuse the
+    /// cache as-is, but a larger one only covers histories up to the old
+    /// bound, so `ensure_history_counts` rebuilds it from scratch.
+    history_counts_max: Option<usize>,
+}
+
+/// The statistical test used to decide whether two candidate states predict
+/// the same future and should therefore be merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeCriterion {
+    /// The original frequentist test: compare next-symbol distributions with
+    /// a chi-square test of homogeneity at significance level `alpha`.
+    ///
+    /// This is numerically fragile on sparse histories, since a single bin
+    /// with `p_b == 0` and `p_a > 0` forces an immediate rejection.
+    ChiSquare { alpha: f64 },
+    /// A Bayesian model-comparison test: under a symmetric Dirichlet(`prior_alpha`)
+    /// prior on next-symbol counts, merge the two candidate states when the
+    /// log Bayes factor for "one shared state" over "two separate states"
+    /// exceeds `ln_threshold`.
+    ///
+    /// Because the Dirichlet-multinomial marginal likelihood is defined for
+    /// any count vector, including all-zero bins, this handles sparse
+    /// histories that the chi-square test mishandles.
+    BayesFactor { prior_alpha: f64, ln_threshold: f64 },
 }
 
 impl CSSR {
@@ -64,18 +105,58 @@ impl CSSR {
         CSSR {
             states: HashSet::new(),
             alphabet,
+            history_counts: HashMap::new(),
+            history_counts_max: None,
+        }
+    }
+
+    /// Builds `history_counts` in a single pass over `data` if it hasn't been
+    /// built yet (or was built for a smaller `max_history`), sliding a
+    /// window of every length `0..=max_history` across `data` and tallying
+    /// the symbol that follows each window. Subsequent calls (e.g.
+    /// re-running with a different `alpha`, or a `max_history` no larger
+    /// than before) reuse the cached counts instead of rescanning `data`.
+    fn ensure_history_counts(&mut self, data: &[u32], max_history: usize) {
+        if self.history_counts_max.is_some_and(|cached| cached >= max_history) {
+            return;
+        }
+
+        self.history_counts.clear();
+        for l in 0..=max_history {
+            if data.len() <= l {
+                continue;
+            }
+            for i in 0..=(data.len() - l - 1) {
+                let history = data[i..i + l].to_vec();
+                let next_symbol = data[i + l];
+                *self
+                    .history_counts
+                    .entry(history)
+                    .or_default()
+                    .entry(next_symbol)
+                    .or_insert(0) += 1;
+            }
         }
+        self.history_counts_max = Some(max_history);
     }
 
-    /// Runs the CSSR algorithm on the given data.
-    pub fn run(&mut self, data: &[u32], max_history: usize, alpha: f32) {
+    /// Runs the CSSR algorithm on the given data, merging candidate states
+    /// according to `criterion`.
+    pub fn run(&mut self, data: &[u32], max_history: usize, criterion: MergeCriterion) {
+        self.ensure_history_counts(data, max_history);
+        self.states.clear();
+
         // 1. Initialize with a single state containing the null history.
         let mut initial_state = CausalState::new();
         initial_state.histories.insert(vec![]); // The empty history
+        initial_state.counts = compute_next_symbol_counts(&initial_state.histories, &self.history_counts);
         initial_state.next_symbol_dist =
-            compute_next_symbol_dist(&initial_state.histories, data, &self.alphabet);
+            compute_next_symbol_dist(&initial_state.histories, &self.history_counts, &self.alphabet);
         self.states.insert(initial_state);
 
+        let mut alphabet_sorted: Vec<u32> = self.alphabet.iter().cloned().collect();
+        alphabet_sorted.sort();
+
         // Main loop for increasing history length L
         for l in 0..max_history {
             let mut new_states: HashSet<CausalState> = HashSet::new();
@@ -97,9 +178,10 @@ impl CSSR {
 
                     if !new_histories.is_empty() {
                         let mut new_state = CausalState::new();
+                        new_state.counts = compute_next_symbol_counts(&new_histories, &self.history_counts);
                         new_state.histories = new_histories;
                         new_state.next_symbol_dist =
-                            compute_next_symbol_dist(&new_state.histories, data, &self.alphabet);
+                            compute_next_symbol_dist(&new_state.histories, &self.history_counts, &self.alphabet);
 
                         // Only add if there is data to support this history
                         if new_state.next_symbol_dist.values().any(|&p| p > 0.0) {
@@ -113,22 +195,42 @@ impl CSSR {
                     continue;
                 }
 
-                let mut merged_states: Vec<CausalState> = Vec::new();
-
-                'outer: while let Some(mut current_state) = potential_new_states.pop() {
-                    for merged_state in &mut merged_states {
-                        let mut alphabet_vec: Vec<_> = self.alphabet.iter().collect();
-                        alphabet_vec.sort();
-
-                        let a_dist: Vec<f64> = alphabet_vec.iter().map(|s| *current_state.next_symbol_dist.get(s).unwrap_or(&0.0) as f64).collect();
-                        let b_dist: Vec<f64> = alphabet_vec.iter().map(|s| *merged_state.next_symbol_dist.get(s).unwrap_or(&0.0) as f64).collect();
+                // Precompute each candidate's sorted distribution vector in
+                // parallel before the sequential merge pass, since every
+                // pairwise comparison below would otherwise redo this work.
+                let mut precomputed_dists: Vec<Vec<f64>> = potential_new_states
+                    .par_iter()
+                    .map(|s| dist_vector(s, &alphabet_sorted))
+                    .collect();
 
-                        if are_distributions_similar(&a_dist, &b_dist, alpha as f64) {
+                let mut merged_states: Vec<CausalState> = Vec::new();
+                let mut merged_dists: Vec<Vec<f64>> = Vec::new();
+
+                'outer: while let (Some(mut current_state), Some(current_dist)) =
+                    (potential_new_states.pop(), precomputed_dists.pop())
+                {
+                    for (merged_state, merged_dist) in merged_states.iter_mut().zip(merged_dists.iter_mut()) {
+                        if should_merge(
+                            &current_dist,
+                            &current_state.counts,
+                            merged_dist,
+                            &merged_state.counts,
+                            &self.alphabet,
+                            criterion,
+                        ) {
                             merged_state.histories.extend(current_state.histories.drain());
-                            merged_state.next_symbol_dist = compute_next_symbol_dist(&merged_state.histories, data, &self.alphabet);
+                            merged_state.counts =
+                                compute_next_symbol_counts(&merged_state.histories, &self.history_counts);
+                            merged_state.next_symbol_dist = compute_next_symbol_dist(
+                                &merged_state.histories,
+                                &self.history_counts,
+                                &self.alphabet,
+                            );
+                            *merged_dist = dist_vector(merged_state, &alphabet_sorted);
                             continue 'outer;
                         }
                     }
+                    merged_dists.push(current_dist);
                     merged_states.push(current_state);
                 }
 
@@ -140,42 +242,463 @@ impl CSSR {
             self.states = new_states;
         }
     }
+
+    /// Runs phase III of CSSR: determinizes the transition function between
+    /// causal states.
+    ///
+    /// For every state `S` and every symbol `a` in the alphabet, this looks at
+    /// where the histories of `S` extended by `a` fall among the states
+    /// produced by homogenization (matching on the longest known suffix). If
+    /// two histories of the same state disagree on the successor state under
+    /// the same symbol, `S` is split along that disagreement, and this
+    /// repeats (splitting every currently-ambiguous state each round) until
+    /// the transition function is a well-defined fixed point.
+    ///
+    /// A split only ever redistributes one state's own histories into ≥2
+    /// disjoint sub-states — it never creates, destroys, or duplicates a
+    /// history — so the total history count across all states is an
+    /// invariant, and the number of states is bounded above by it. Since
+    /// every round that finds an ambiguity strictly increases the state
+    /// count, the loop is guaranteed to reach a fixed point in at most
+    /// `total_histories` rounds; `max_rounds` below turns a violation of
+    /// that invariant (a real bug) into an immediate panic instead of a
+    /// silent hang.
+    ///
+    /// Both the `states` snapshot taken each round and the alphabet are
+    /// sorted into a canonical order first, so which (state, symbol)
+    /// ambiguities are found and how they're resolved no longer depends on
+    /// the process's randomly-seeded `HashSet`/`HashMap` iteration order —
+    /// two runs on identical input always split in the same order.
+    pub fn build_machine(&mut self, data: &[u32]) -> EpsilonMachine {
+        let max_history = self
+            .states
+            .iter()
+            .flat_map(|s| s.histories.iter())
+            .map(|h| h.len())
+            .max()
+            .unwrap_or(0);
+        self.ensure_history_counts(data, max_history);
+
+        let total_histories: usize = self.states.iter().map(|s| s.histories.len()).sum();
+        let max_rounds = total_histories + 1;
+
+        for round in 0..=max_rounds {
+            assert!(
+                round < max_rounds,
+                "build_machine failed to reach a fixed point within {max_rounds} rounds \
+                 ({total_histories} total histories); a split must have violated the \
+                 history-partition invariant it relies on to converge"
+            );
+
+            let mut states: Vec<CausalState> = self.states.iter().cloned().collect();
+            states.sort_by_key(canonical_histories);
+
+            let mut alphabet_sorted: Vec<u32> = self.alphabet.iter().cloned().collect();
+            alphabet_sorted.sort();
+
+            let mut transitions: HashMap<(usize, u32), usize> = HashMap::new();
+            let mut splits: Vec<(usize, u32)> = Vec::new();
+
+            for (i, state) in states.iter().enumerate() {
+                for &symbol in &alphabet_sorted {
+                    let mut successor: Option<usize> = None;
+                    let mut ambiguous = false;
+                    for history in &state.histories {
+                        let mut extended = history.clone();
+                        extended.push(symbol);
+                        if let Some(s) = find_successor_state(&extended, &states) {
+                            match successor {
+                                None => successor = Some(s),
+                                Some(prev) if prev != s => {
+                                    ambiguous = true;
+                                    break;
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    if ambiguous {
+                        splits.push((i, symbol));
+                        break;
+                    }
+                    if let Some(s) = successor {
+                        transitions.insert((i, symbol), s);
+                    }
+                }
+            }
+
+            if !splits.is_empty() {
+                // Every entry targets a different state (each state
+                // contributes at most one ambiguous symbol per round), and a
+                // split only touches its own state's histories, so all of
+                // them can be applied against the same pre-round snapshot
+                // without interfering with one another.
+                for (state_idx, symbol) in splits {
+                    self.split_ambiguous_state(state_idx, symbol, &states);
+                }
+                continue;
+            }
+
+            // The null-history state from phase I rarely survives
+            // homogenization intact (it's replaced by length-1 states as
+            // soon as max_history >= 1), so looking it up by its original
+            // empty-history key no longer finds anything. Instead, resolve
+            // `start` the same way any other transition is resolved: match
+            // the longest known suffix, this time of the data's own leading
+            // prefix, which is well-defined and content-addressed rather
+            // than an arbitrary `HashSet` iteration order.
+            let prefix_len = max_history.min(data.len());
+            let start = find_successor_state(&data[..prefix_len], &states)
+                .expect("some state must match a prefix of the training data");
+            return EpsilonMachine {
+                states,
+                transitions,
+                start,
+            };
+        }
+
+        unreachable!("the loop above always returns or panics before exhausting its range")
+    }
+
+    /// Splits `states[state_idx]` into one sub-state per distinct successor
+    /// reached under `symbol`, replacing it in `self.states`.
+    fn split_ambiguous_state(&mut self, state_idx: usize, symbol: u32, states: &[CausalState]) {
+        let ambiguous_state = &states[state_idx];
+        let mut groups: HashMap<Option<usize>, HashSet<History>> = HashMap::new();
+
+        for history in &ambiguous_state.histories {
+            let mut extended = history.clone();
+            extended.push(symbol);
+            let successor = find_successor_state(&extended, states);
+            groups.entry(successor).or_default().insert(history.clone());
+        }
+
+        self.states.remove(ambiguous_state);
+        for (_, histories) in groups {
+            let counts = compute_next_symbol_counts(&histories, &self.history_counts);
+            let next_symbol_dist = compute_next_symbol_dist(&histories, &self.history_counts, &self.alphabet);
+            self.states.insert(CausalState {
+                histories,
+                next_symbol_dist,
+                counts,
+            });
+        }
+    }
+
+    /// Estimates the entropy rate hμ with Aitken Δ² acceleration over history
+    /// length, returning `(raw, accelerated)`.
+    ///
+    /// `entropy_rate` computed at a fixed `max_history` converges to the true
+    /// hμ slowly as history length grows, so this reconstructs fresh machines
+    /// at `max_history`, `max_history + 1` and `max_history + 2` to get
+    /// `h(L)`, `h(L+1)`, `h(L+2)`, then extrapolates with
+    /// `ĥ = h_L − (h_{L+1} − h_L)² / (h_{L+2} − 2·h_{L+1} + h_L)`, falling back
+    /// to the raw `h(L+2)` estimate when that denominator is near zero.
+    pub fn entropy_rate_extrapolated(
+        &self,
+        data: &[u32],
+        max_history: usize,
+        criterion: MergeCriterion,
+    ) -> (f64, f64) {
+        let estimate_at = |l: usize| -> f64 {
+            let mut cssr = CSSR::new(self.alphabet.clone());
+            cssr.run(data, l, criterion);
+            cssr.build_machine(data).entropy_rate()
+        };
+
+        let h_l = estimate_at(max_history);
+        let h_l1 = estimate_at(max_history + 1);
+        let h_l2 = estimate_at(max_history + 2);
+
+        let denominator = h_l2 - 2.0 * h_l1 + h_l;
+        let accelerated = if denominator.abs() < 1e-9 {
+            h_l2
+        } else {
+            h_l - (h_l1 - h_l).powi(2) / denominator
+        };
+
+        (h_l, accelerated)
+    }
 }
 
-/// Computes the probability distribution of the next symbol given a set of histories.
-fn compute_next_symbol_dist(
-    histories: &HashSet<History>,
-    data: &[u32],
-    alphabet: &HashSet<u32>,
-) -> HashMap<u32, f32> {
-    let mut counts: HashMap<u32, u32> = HashMap::new();
-    let mut total_count = 0;
+/// A deterministic sort key for a causal state: its histories, sorted.
+///
+/// `build_machine` sorts its per-round `Vec<CausalState>` snapshot by this
+/// key so that state order (and therefore which state `find_successor_state`
+/// matches when, in principle, more than one could) is a pure function of
+/// the states' content, not of the process's randomly-seeded `HashSet`
+/// iteration order.
+fn canonical_histories(state: &CausalState) -> Vec<History> {
+    let mut histories: Vec<History> = state.histories.iter().cloned().collect();
+    histories.sort();
+    histories
+}
 
-    if histories.is_empty() {
-        return alphabet.iter().map(|&s| (s, 0.0)).collect();
+/// Finds the state whose histories contain the longest suffix of `history`.
+///
+/// This is the core of CSSR's determinization step: the successor of a
+/// history extended by a new symbol is the state matching the longest known
+/// suffix of that extended history, falling back to shorter suffixes (down to
+/// the empty history) when no longer match exists.
+fn find_successor_state(history: &[u32], states: &[CausalState]) -> Option<usize> {
+    for l in (0..=history.len()).rev() {
+        let suffix = &history[history.len() - l..];
+        if let Some(i) = states.iter().position(|s| s.histories.contains(suffix)) {
+            return Some(i);
+        }
     }
+    None
+}
+
+/// A deterministic finite-state machine reconstructed from a converged set of
+/// causal states.
+///
+/// Homogenization alone only produces the states themselves; `EpsilonMachine`
+/// additionally carries the transition function between them, computed by
+/// [`CSSR::build_machine`], turning the result into a usable ε-machine.
+#[derive(Debug, Clone)]
+pub struct EpsilonMachine {
+    /// The causal states, indexed by position for transition lookups.
+    pub states: Vec<CausalState>,
+    /// Maps `(state index, symbol)` to the index of the successor state.
+    pub transitions: HashMap<(usize, u32), usize>,
+    /// Index of the start state: the state matching the longest suffix of the
+    /// training data's first `L` symbols, where `L` is the longest history
+    /// length among the states.
+    pub start: usize,
+}
 
-    let history_len = histories.iter().next().unwrap().len();
+impl EpsilonMachine {
+    /// Generates `n` symbols of synthetic data by simulating the ε-machine.
+    ///
+    /// Starting at `start`, each step draws the next symbol from the current
+    /// state's `next_symbol_dist` via inverse-CDF sampling and follows the
+    /// recorded transition to the successor state. Pass a seeded `rng` (e.g.
+    /// `ChaCha20Rng::seed_from_u64`) for reproducible runs.
+    pub fn sample<R: rand::Rng>(&self, rng: &mut R, n: usize) -> Vec<u32> {
+        let mut output = Vec::with_capacity(n);
+        let mut current = self.start;
+
+        for _ in 0..n {
+            let state = &self.states[current];
+            let mut symbols: Vec<_> = state.next_symbol_dist.iter().collect();
+            symbols.sort_by_key(|(symbol, _)| **symbol);
+
+            let draw = rng.gen::<f64>();
+            let mut cumulative = 0.0;
+            let mut chosen = symbols.last().map(|(symbol, _)| **symbol).unwrap_or(0);
+            for (&symbol, &p) in symbols {
+                cumulative += p as f64;
+                if draw < cumulative {
+                    chosen = symbol;
+                    break;
+                }
+            }
 
-    if history_len == 0 {
-        for symbol in data {
-            *counts.entry(*symbol).or_insert(0) += 1;
-            total_count += 1;
+            output.push(chosen);
+            if let Some(&next) = self.transitions.get(&(current, chosen)) {
+                current = next;
+            }
         }
-    } else {
-        let history_set: HashSet<&[u32]> = histories.iter().map(|h| h.as_slice()).collect();
-        if data.len() > history_len {
-            for i in 0..=(data.len() - history_len - 1) {
-                let history_slice = &data[i..i + history_len];
-                if history_set.contains(history_slice) {
-                    let next_symbol = data[i + history_len];
-                    *counts.entry(next_symbol).or_insert(0) += 1;
-                    total_count += 1;
+
+        output
+    }
+
+    /// Statistical complexity Cμ = −Σ_S π(S) log₂ π(S), the Shannon entropy of
+    /// the stationary distribution π over causal states.
+    pub fn statistical_complexity(&self) -> f64 {
+        self.stationary_distribution()
+            .into_iter()
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.log2())
+            .sum()
+    }
+
+    /// Entropy rate hμ = Σ_S π(S)·(−Σ_a P(a|S) log₂ P(a|S)), the stationary
+    /// average of each state's next-symbol uncertainty.
+    ///
+    /// This estimate is biased at a finite `max_history`; prefer
+    /// `CSSR::entropy_rate_extrapolated` when a less biased value is needed.
+    pub fn entropy_rate(&self) -> f64 {
+        self.stationary_distribution()
+            .iter()
+            .zip(self.states.iter())
+            .map(|(&p, state)| p * state_entropy(state))
+            .sum()
+    }
+
+    /// Row-stochastic state-to-state transition matrix `P[i][j] = P(S_j | S_i)`,
+    /// obtained by summing the next-symbol probabilities of every symbol whose
+    /// transition leads from state `i` to state `j`.
+    fn transition_probabilities(&self) -> Vec<Vec<f64>> {
+        let n = self.states.len();
+        let mut p = vec![vec![0.0; n]; n];
+        for (i, state) in self.states.iter().enumerate() {
+            for (&symbol, &prob) in &state.next_symbol_dist {
+                if let Some(&j) = self.transitions.get(&(i, symbol)) {
+                    p[i][j] += prob as f64;
                 }
             }
         }
+        p
     }
 
+    /// The stationary distribution π of the transition matrix, found by power
+    /// iteration on the lazy chain `P' = (P + I)/2` (renormalizing to absorb
+    /// rounding drift) until it stops changing.
+    ///
+    /// Plain power iteration on `P` itself only converges for aperiodic
+    /// chains; a periodic transition structure (e.g. a pure permutation
+    /// matrix with period > 1) makes `π ← π·P` oscillate forever instead of
+    /// settling. Averaging in the identity adds a self-loop to every state,
+    /// which breaks periodicity while leaving `π` — the left eigenvector for
+    /// eigenvalue 1 — unchanged, since `π·(P+I)/2 = π` iff `π·P = π`.
+    fn stationary_distribution(&self) -> Vec<f64> {
+        let p = self.transition_probabilities();
+        let n = p.len();
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let mut pi = vec![1.0 / n as f64; n];
+        for _ in 0..10_000 {
+            let mut next = vec![0.0; n];
+            for (i, row) in p.iter().enumerate() {
+                for (j, &p_ij) in row.iter().enumerate() {
+                    let lazy = if i == j { (p_ij + 1.0) / 2.0 } else { p_ij / 2.0 };
+                    next[j] += pi[i] * lazy;
+                }
+            }
+
+            let norm: f64 = next.iter().sum();
+            if norm > 0.0 {
+                for v in &mut next {
+                    *v /= norm;
+                }
+            }
+
+            let delta: f64 = next.iter().zip(&pi).map(|(a, b)| (a - b).abs()).sum();
+            pi = next;
+            if delta < 1e-12 {
+                break;
+            }
+        }
+
+        pi
+    }
+
+    /// Renders the ε-machine as a GraphViz `digraph`: one node per causal
+    /// state (labeled with its shortest history) and one edge per transition,
+    /// annotated with `symbol (p)` from that state's next-symbol
+    /// distribution. Pass `labels` (as returned by the char/token input
+    /// tokenizer) to render original symbols instead of raw ids.
+    pub fn to_dot(&self, labels: Option<&HashMap<u32, String>>) -> String {
+        let mut dot = String::from("digraph epsilon_machine {\n    rankdir=LR;\n");
+
+        for (i, state) in self.states.iter().enumerate() {
+            let representative = state
+                .histories
+                .iter()
+                .min_by_key(|h| h.len())
+                .map(|h| render_history(h, labels))
+                .unwrap_or_default();
+            let shape = if i == self.start { "doublecircle" } else { "circle" };
+            dot.push_str(&format!(
+                "    {} [label=\"S{} [{}]\", shape={}];\n",
+                i, i, representative, shape
+            ));
+        }
+
+        let mut edges: Vec<_> = self.transitions.iter().collect();
+        edges.sort_by_key(|(&(from, symbol), _)| (from, symbol));
+        for (&(from, symbol), &to) in edges {
+            let p = self.states[from]
+                .next_symbol_dist
+                .get(&symbol)
+                .copied()
+                .unwrap_or(0.0);
+            let symbol_label = render_symbol(symbol, labels);
+            dot.push_str(&format!(
+                "    {} -> {} [label=\"{} ({:.3})\"];\n",
+                from, to, symbol_label, p
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The aggregate row-stochastic transition matrix between causal states
+    /// (`P[i][j] = P(S_j | S_i)`), alongside the sorted alphabet. Combined
+    /// with each state's `next_symbol_dist`, this is the transition/emission
+    /// pair downstream HMM tooling expects.
+    pub fn to_transition_matrix(&self) -> (Vec<Vec<f64>>, Vec<u32>) {
+        let mut alphabet: Vec<u32> = self
+            .states
+            .iter()
+            .flat_map(|s| s.next_symbol_dist.keys().copied())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        alphabet.sort();
+
+        (self.transition_probabilities(), alphabet)
+    }
+}
+
+/// Renders a single symbol using `labels` if available, falling back to its
+/// raw numeric id, with any DOT `label="..."` metacharacters escaped so
+/// tokens from the char/token tokenizer can't corrupt the surrounding
+/// quoted string.
+fn render_symbol(symbol: u32, labels: Option<&HashMap<u32, String>>) -> String {
+    let rendered = labels
+        .and_then(|l| l.get(&symbol))
+        .cloned()
+        .unwrap_or_else(|| symbol.to_string());
+    escape_dot_label(&rendered)
+}
+
+/// Renders a history as a concatenation of its symbols' labels.
+fn render_history(history: &[u32], labels: Option<&HashMap<u32, String>>) -> String {
+    history
+        .iter()
+        .map(|&s| render_symbol(s, labels))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+/// Escapes `\` and `"` so `text` can be embedded inside a DOT `label="..."`
+/// string without breaking out of it.
+fn escape_dot_label(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Shannon entropy (in bits) of a state's next-symbol distribution:
+/// `−Σ_a P(a|S) log₂ P(a|S)`.
+fn state_entropy(state: &CausalState) -> f64 {
+    -state
+        .next_symbol_dist
+        .values()
+        .filter(|&&p| p > 0.0)
+        .map(|&p| (p as f64) * (p as f64).log2())
+        .sum::<f64>()
+}
+
+/// Computes the probability distribution of the next symbol given a set of
+/// histories, pooling pre-counted occurrences from `history_counts` instead
+/// of rescanning the original data.
+fn compute_next_symbol_dist(
+    histories: &HashSet<History>,
+    history_counts: &HashMap<History, HashMap<u32, u32>>,
+    alphabet: &HashSet<u32>,
+) -> HashMap<u32, f32> {
+    if histories.is_empty() {
+        return alphabet.iter().map(|&s| (s, 0.0)).collect();
+    }
+
+    let counts = compute_next_symbol_counts(histories, history_counts);
+    let total_count: u32 = counts.values().sum();
+
     let mut dist = HashMap::new();
     if total_count > 0 {
         for (&symbol, &count) in &counts {
@@ -190,6 +713,101 @@ fn compute_next_symbol_dist(
     dist
 }
 
+/// Pools the pre-counted next-symbol occurrences of a set of histories by
+/// looking each one up in `history_counts` (built once by
+/// `CSSR::ensure_history_counts`), rather than rescanning the data. Backs
+/// both `compute_next_symbol_dist` and the Bayesian merge criterion, which
+/// needs raw counts rather than normalized floats.
+fn compute_next_symbol_counts(
+    histories: &HashSet<History>,
+    history_counts: &HashMap<History, HashMap<u32, u32>>,
+) -> HashMap<u32, u32> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+
+    for history in histories {
+        if let Some(next_counts) = history_counts.get(history) {
+            for (&symbol, &count) in next_counts {
+                *counts.entry(symbol).or_insert(0) += count;
+            }
+        }
+    }
+
+    counts
+}
+
+/// Builds the sorted-alphabet distribution vector used by the chi-square
+/// merge test, so it can be precomputed once per candidate state instead of
+/// once per pairwise comparison.
+fn dist_vector(state: &CausalState, alphabet_sorted: &[u32]) -> Vec<f64> {
+    alphabet_sorted
+        .iter()
+        .map(|s| *state.next_symbol_dist.get(s).unwrap_or(&0.0) as f64)
+        .collect()
+}
+
+/// Decides whether two candidate states, given their (precomputed)
+/// distribution vectors and counts, predict the same future closely enough
+/// to be merged, under the given `criterion`.
+fn should_merge(
+    a_dist: &[f64],
+    a_counts: &HashMap<u32, u32>,
+    b_dist: &[f64],
+    b_counts: &HashMap<u32, u32>,
+    alphabet: &HashSet<u32>,
+    criterion: MergeCriterion,
+) -> bool {
+    match criterion {
+        MergeCriterion::ChiSquare { alpha } => are_distributions_similar(a_dist, b_dist, alpha),
+        MergeCriterion::BayesFactor {
+            prior_alpha,
+            ln_threshold,
+        } => log_bayes_factor(a_counts, b_counts, alphabet, prior_alpha) > ln_threshold,
+    }
+}
+
+/// Log marginal likelihood of symbol counts `n` under a symmetric
+/// Dirichlet(`alpha`) prior over a `k`-symbol alphabet:
+/// `ln P(n) = ln Γ(kα) - ln Γ(N+kα) + Σ_i [ln Γ(n_i+α) - ln Γ(α)]`.
+fn log_dirichlet_multinomial_likelihood(
+    counts: &HashMap<u32, u32>,
+    alphabet: &HashSet<u32>,
+    alpha: f64,
+) -> f64 {
+    let k = alphabet.len() as f64;
+    let n: f64 = alphabet
+        .iter()
+        .map(|s| *counts.get(s).unwrap_or(&0) as f64)
+        .sum();
+
+    let mut result = ln_gamma(k * alpha) - ln_gamma(n + k * alpha);
+    for symbol in alphabet {
+        let n_i = *counts.get(symbol).unwrap_or(&0) as f64;
+        result += ln_gamma(n_i + alpha) - ln_gamma(alpha);
+    }
+    result
+}
+
+/// Log Bayes factor for merging two candidate states with counts `counts_a`
+/// and `counts_b` into one: `ln BF = ln P(n_A + n_B) - [ln P(n_A) + ln P(n_B)]`.
+/// A positive value favors the single merged state.
+fn log_bayes_factor(
+    counts_a: &HashMap<u32, u32>,
+    counts_b: &HashMap<u32, u32>,
+    alphabet: &HashSet<u32>,
+    alpha: f64,
+) -> f64 {
+    let mut combined = counts_a.clone();
+    for (&symbol, &count) in counts_b {
+        *combined.entry(symbol).or_insert(0) += count;
+    }
+
+    let log_p_combined = log_dirichlet_multinomial_likelihood(&combined, alphabet, alpha);
+    let log_p_a = log_dirichlet_multinomial_likelihood(counts_a, alphabet, alpha);
+    let log_p_b = log_dirichlet_multinomial_likelihood(counts_b, alphabet, alpha);
+
+    log_p_combined - (log_p_a + log_p_b)
+}
+
 
 /// Performs a chi-square statistical test to determine if two distributions are similar.
 ///
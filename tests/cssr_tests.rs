@@ -1,5 +1,25 @@
-use rust_cssr::{are_distributions_similar, CSSR};
-use std::collections::HashSet;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rust_cssr::{are_distributions_similar, CausalState, EpsilonMachine, MergeCriterion, CSSR};
+use std::collections::{HashMap, HashSet};
+
+/// The alternating `0,1,0,1,...` pattern used throughout this file, repeated
+/// enough times that higher `max_history` values still have data to work
+/// with (the tests that spell it out inline only repeat it 5 times).
+fn alternating_sequence() -> Vec<u32> {
+    (0..40).map(|i| i % 2).collect()
+}
+
+/// A 44-symbol, 3-letter pseudo-random sequence with no periodic structure,
+/// used to stress multi-round splitting in `build_machine` at a
+/// `max_history` greater than 1 — every other test in this file only ever
+/// builds machines from the period-2 alternating sequence, which converges
+/// in a single determinization round and can't catch a non-terminating
+/// split loop.
+fn pseudo_random_sequence() -> Vec<u32> {
+    let mut rng = ChaCha20Rng::seed_from_u64(7);
+    (0..44).map(|_| rng.gen_range(0..3)).collect()
+}
 
 #[test]
 fn test_are_distributions_similar() {
@@ -20,7 +40,7 @@ fn test_cssr_simple_sequence() {
     let mut cssr = CSSR::new(alphabet);
 
     // Run CSSR with a max history of 1 and a high alpha to force splits.
-    cssr.run(&data, 1, 0.01);
+    cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
 
     // There should be two causal states:
     // 1. After a 0, the next symbol is always 1.
@@ -51,3 +71,247 @@ fn test_cssr_simple_sequence() {
     assert_eq!(*state_defs[1].1[1].0, 1);
     assert_eq!(*state_defs[1].1[1].1, 0.0);
 }
+
+#[test]
+fn test_cssr_bayes_factor_merge() {
+    // Same alternating pattern as `test_cssr_simple_sequence`, but merged
+    // with the Bayesian criterion instead of chi-square.
+    let data = vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1];
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet);
+
+    cssr.run(
+        &data,
+        1,
+        MergeCriterion::BayesFactor {
+            prior_alpha: 1.0,
+            ln_threshold: 0.0,
+        },
+    );
+
+    // The two histories predict opposite futures with no uncertainty, so
+    // even a weak prior should keep them as distinct states.
+    assert_eq!(cssr.states.len(), 2);
+}
+
+#[test]
+fn test_sample_is_reproducible_across_runs() {
+    // Same data, same seed, two independently reconstructed machines:
+    // `build_machine` must resolve `start` the same way every time, or the
+    // seed alone can't make `sample` reproducible.
+    let data = vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1];
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+
+    let run = || {
+        let mut cssr = CSSR::new(alphabet.clone());
+        cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+        let machine = cssr.build_machine(&data);
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        machine.sample(&mut rng, 20)
+    };
+
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn test_run_reuses_cache_correctly_across_growing_max_history() {
+    // The history-count cache built for a small `max_history` only covers
+    // windows up to that length. Re-running the same `CSSR` instance with a
+    // larger `max_history` must rebuild it, not silently keep serving the
+    // stale, shorter-range counts.
+    let data = alternating_sequence();
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+
+    let mut reused = CSSR::new(alphabet.clone());
+    reused.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+    reused.run(&data, 3, MergeCriterion::ChiSquare { alpha: 0.01 });
+
+    let mut fresh = CSSR::new(alphabet);
+    fresh.run(&data, 3, MergeCriterion::ChiSquare { alpha: 0.01 });
+
+    let mut reused_states: Vec<_> = reused
+        .states
+        .iter()
+        .map(|s| {
+            let mut histories: Vec<_> = s.histories.iter().cloned().collect();
+            histories.sort();
+            histories
+        })
+        .collect();
+    reused_states.sort();
+
+    let mut fresh_states: Vec<_> = fresh
+        .states
+        .iter()
+        .map(|s| {
+            let mut histories: Vec<_> = s.histories.iter().cloned().collect();
+            histories.sort();
+            histories
+        })
+        .collect();
+    fresh_states.sort();
+
+    assert_eq!(reused_states, fresh_states);
+}
+
+#[test]
+fn test_build_machine_transitions() {
+    // The period-2 alternation has exactly one live transition per state:
+    // after a 0 it must see a 1 next, and vice versa.
+    let data = vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1];
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet);
+    cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+    let machine = cssr.build_machine(&data);
+
+    assert_eq!(machine.states.len(), 2);
+
+    let state_after_0 = machine
+        .states
+        .iter()
+        .position(|s| s.histories.contains(&vec![0]))
+        .unwrap();
+    let state_after_1 = machine
+        .states
+        .iter()
+        .position(|s| s.histories.contains(&vec![1]))
+        .unwrap();
+
+    assert_eq!(machine.transitions[&(state_after_0, 1)], state_after_1);
+    assert_eq!(machine.transitions[&(state_after_1, 0)], state_after_0);
+
+    // `start` must deterministically match the data's own leading history
+    // (here, a `0`), not an arbitrary `HashSet` iteration order.
+    assert_eq!(machine.start, state_after_0);
+}
+
+#[test]
+fn test_build_machine_converges_on_non_periodic_data_with_multi_round_splitting() {
+    // Regression test for a determinization loop that could churn through
+    // many rounds of splitting (or, with an unlucky process hash seed,
+    // effectively never terminate) on non-periodic data at a `max_history`
+    // large enough to require several rounds of splitting to reach a fixed
+    // point. This just needs to return in bounded time with a well-formed
+    // machine; it isn't asserting a specific reconstructed structure.
+    let data = pseudo_random_sequence();
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet.clone());
+    cssr.run(&data, 6, MergeCriterion::ChiSquare { alpha: 0.01 });
+    let machine = cssr.build_machine(&data);
+
+    assert!(!machine.states.is_empty());
+    assert!(machine.start < machine.states.len());
+    for (&(from, symbol), &to) in &machine.transitions {
+        assert!(from < machine.states.len());
+        assert!(to < machine.states.len());
+        assert!(alphabet.contains(&symbol));
+    }
+}
+
+#[test]
+fn test_statistical_complexity_and_entropy_rate() {
+    // A perfectly alternating, noise-free sequence has zero uncertainty
+    // once you know which causal state you're in (entropy rate 0), but two
+    // causal states used with equal frequency (statistical complexity 1 bit).
+    let data = alternating_sequence();
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet);
+    cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+    let machine = cssr.build_machine(&data);
+
+    assert_eq!(machine.statistical_complexity(), 1.0);
+    assert_eq!(machine.entropy_rate(), 0.0);
+}
+
+#[test]
+fn test_entropy_rate_extrapolated_matches_raw_on_deterministic_data() {
+    // With no noise to extrapolate away, the Aitken-accelerated estimate
+    // should agree with the raw one.
+    let data = alternating_sequence();
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let cssr = CSSR::new(alphabet);
+
+    let (raw, accelerated) =
+        cssr.entropy_rate_extrapolated(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+
+    assert_eq!(raw, 0.0);
+    assert_eq!(accelerated, 0.0);
+}
+
+#[test]
+fn test_to_dot_escapes_quotes_and_backslashes() {
+    // Regression test: tokens fed in via the char/token tokenizer can
+    // contain `"` or `\`, which must not be allowed to break out of the
+    // DOT `label="..."` string.
+    let data = vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1];
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet);
+    cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+    let machine = cssr.build_machine(&data);
+
+    let mut labels = HashMap::new();
+    labels.insert(0, "a\"".to_string());
+    labels.insert(1, "b\\c".to_string());
+
+    let dot = machine.to_dot(Some(&labels));
+
+    // The embedded `"` and `\` must come through escaped...
+    assert!(dot.contains("a\\\""));
+    assert!(dot.contains("b\\\\c"));
+    // ...and every edge's `label="..."` must still close on its own line:
+    // an unescaped `"` would instead terminate the string early. (Node
+    // lines also carry a `label="..."` but are followed by `, shape=...`,
+    // so only edge lines close directly on the quote.)
+    for line in dot.lines().filter(|l| l.contains(" -> ")) {
+        assert!(line.trim_end().ends_with("\"];"));
+    }
+}
+
+#[test]
+fn test_stationary_distribution_converges_on_periodic_non_permutation_chain() {
+    // A -> B or A -> C with equal probability, B -> A, C -> A. The A->A
+    // transition matrix entry is always 0, so this is periodic (period 2)
+    // but *not* a permutation matrix, unlike the alternating two-state
+    // machine used elsewhere in this file. Plain power iteration oscillates
+    // on a chain like this, returning to the uniform starting guess every
+    // other step; only a damped iteration converges to the true
+    // π = [0.5, 0.25, 0.25].
+    let mut a = CausalState::new();
+    a.next_symbol_dist.insert(1, 0.5);
+    a.next_symbol_dist.insert(2, 0.5);
+
+    let mut b = CausalState::new();
+    b.next_symbol_dist.insert(0, 1.0);
+
+    let mut c = CausalState::new();
+    c.next_symbol_dist.insert(0, 1.0);
+
+    let machine = EpsilonMachine {
+        states: vec![a, b, c],
+        transitions: HashMap::from([((0, 1), 1), ((0, 2), 2), ((1, 0), 0), ((2, 0), 0)]),
+        start: 0,
+    };
+
+    // Cμ = -Σ π log2(π) for π = [0.5, 0.25, 0.25] is 1.5 bits.
+    assert!((machine.statistical_complexity() - 1.5).abs() < 1e-6);
+    // hμ = 0.5*1 + 0.25*0 + 0.25*0 (A is the only state with uncertainty).
+    assert!((machine.entropy_rate() - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_to_transition_matrix_is_row_stochastic() {
+    let data = vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1];
+    let alphabet: HashSet<u32> = data.iter().cloned().collect();
+    let mut cssr = CSSR::new(alphabet);
+    cssr.run(&data, 1, MergeCriterion::ChiSquare { alpha: 0.01 });
+    let machine = cssr.build_machine(&data);
+
+    let (matrix, symbols) = machine.to_transition_matrix();
+
+    assert_eq!(matrix.len(), machine.states.len());
+    assert_eq!(symbols, vec![0, 1]);
+    for row in &matrix {
+        let sum: f64 = row.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-9);
+    }
+}